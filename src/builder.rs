@@ -1,14 +1,20 @@
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, format, sync::Arc};
+#[cfg(feature = "std-net")]
 use pnet::datalink;
-use std::{
-    net::{IpAddr, Ipv4Addr},
-    sync::{Arc, Mutex},
-};
+#[cfg(feature = "std-net")]
+use std::net::{IpAddr, Ipv4Addr};
+#[cfg(feature = "std")]
+use std::sync::Arc;
 use time::macros::datetime;
 use time::OffsetDateTime;
 
+#[cfg(feature = "std")]
+use crate::clock::SystemClock;
 use crate::{
+    clock::Clock,
     error::{BoxDynError, Error},
-    sonyflake::{to_sonyflake_time, Internals, SharedSonyflake, Sonyflake, BIT_LEN_SEQUENCE},
+    sonyflake::{to_sonyflake_time, BitLen, Internals, SharedSonyflake, Sonyflake},
 };
 
 /// A builder to build a [`Sonyflake`] generator.
@@ -18,6 +24,8 @@ pub struct Builder<'a> {
     start_time: Option<OffsetDateTime>,
     machine_id: Option<&'a dyn Fn() -> Result<u16, BoxDynError>>,
     check_machine_id: Option<&'a dyn Fn(u16) -> bool>,
+    bit_lengths: Option<(u8, u8)>,
+    clock: Option<Box<dyn Clock>>,
 }
 
 impl<'a> Default for Builder<'a> {
@@ -35,6 +43,8 @@ impl<'a> Builder<'a> {
             start_time: None,
             machine_id: None,
             check_machine_id: None,
+            bit_lengths: None,
+            clock: None,
         }
     }
 
@@ -59,12 +69,75 @@ impl<'a> Builder<'a> {
         self
     }
 
+    /// Configure a custom bit-width allocation for the time and sequence
+    /// components, instead of the default 39/9 split. The machine id width
+    /// is derived as `64 - time - sequence`.
+    ///
+    /// `finalize` rejects configurations where `time + sequence` exceeds 64
+    /// bits, or where the resulting `machine_id` doesn't fit in its derived
+    /// width, returning [`Error::InvalidBitLength`].
+    pub fn bit_lengths(mut self, time: u8, sequence: u8) -> Self {
+        self.bit_lengths = Some((time, sequence));
+        self
+    }
+
+    /// Inject a custom [`Clock`] for the generator to read time from, instead
+    /// of the real-time [`SystemClock`] (the default when the `std` feature
+    /// is enabled).
+    ///
+    /// Under `no_std`, a `SystemClock` is unavailable, so a clock must be
+    /// supplied via this method or `finalize` returns [`Error::ClockRequired`].
+    /// A [`ManualClock`] is useful here, and for deterministic tests under
+    /// `std` too.
+    ///
+    /// [`Clock`]: crate::Clock
+    /// [`SystemClock`]: crate::SystemClock
+    /// [`ManualClock`]: crate::ManualClock
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
     /// Finalize the builder to create a Sonyflake.
     pub fn finalize(self) -> Result<Sonyflake, Error> {
-        let sequence = 1 << (BIT_LEN_SEQUENCE - 1);
+        let bit_len = if let Some((time, sequence)) = self.bit_lengths {
+            let total = time as u16 + sequence as u16;
+            if time == 0 || sequence == 0 || total > 64 {
+                return Err(Error::InvalidBitLength(format!(
+                    "time ({time}) + sequence ({sequence}) bits must be non-zero and sum to at most 64"
+                )));
+            }
+            if sequence > 16 {
+                return Err(Error::InvalidBitLength(format!(
+                    "sequence ({sequence}) bits must be at most 16, since the sequence counter is stored in a u16"
+                )));
+            }
+            BitLen {
+                time,
+                sequence,
+                machine_id: (64 - total) as u8,
+            }
+        } else {
+            BitLen::default()
+        };
+
+        let sequence = 1 << (bit_len.sequence - 1);
+
+        let clock = if let Some(clock) = self.clock {
+            clock
+        } else {
+            #[cfg(feature = "std")]
+            {
+                Box::new(SystemClock)
+            }
+            #[cfg(not(feature = "std"))]
+            {
+                return Err(Error::ClockRequired);
+            }
+        };
 
         let start_time = if let Some(start_time) = self.start_time {
-            if start_time > OffsetDateTime::now_utc() {
+            if start_time > clock.now() {
                 return Err(Error::StartTimeAheadOfCurrentTime(start_time));
             }
 
@@ -79,9 +152,23 @@ impl<'a> Builder<'a> {
                 Err(e) => return Err(Error::MachineIdFailed(e)),
             }
         } else {
-            lower_16_bit_private_ip()?
+            #[cfg(feature = "std-net")]
+            {
+                lower_16_bit_private_ip()?
+            }
+            #[cfg(not(feature = "std-net"))]
+            {
+                return Err(Error::MachineIdRequired);
+            }
         };
 
+        if machine_id as u64 > bit_len.mask_machine_id() {
+            return Err(Error::InvalidBitLength(format!(
+                "machine_id {} does not fit in {} bits",
+                machine_id, bit_len.machine_id
+            )));
+        }
+
         if let Some(check_machine_id) = self.check_machine_id {
             if !check_machine_id(machine_id) {
                 return Err(Error::CheckMachineIdFailed);
@@ -89,17 +176,20 @@ impl<'a> Builder<'a> {
         }
 
         let shared = Arc::new(SharedSonyflake {
-            internals: Mutex::new(Internals {
+            internals: SharedSonyflake::new_internals_lock(Internals {
+                start_time,
                 sequence,
                 elapsed_time: 0,
             }),
-            start_time,
             machine_id,
+            bit_len,
+            clock,
         });
         Ok(Sonyflake::new_inner(shared))
     }
 }
 
+#[cfg(feature = "std-net")]
 fn private_ipv4() -> Option<Ipv4Addr> {
     datalink::interfaces()
         .iter()
@@ -122,6 +212,7 @@ fn private_ipv4() -> Option<Ipv4Addr> {
         .flatten()
 }
 
+#[cfg(feature = "std-net")]
 fn is_private_ipv4(ip: Ipv4Addr) -> bool {
     let octets = ip.octets();
     octets[0] == 10
@@ -129,6 +220,7 @@ fn is_private_ipv4(ip: Ipv4Addr) -> bool {
         || octets[0] == 192 && octets[1] == 168
 }
 
+#[cfg(feature = "std-net")]
 pub(crate) fn lower_16_bit_private_ip() -> Result<u16, Error> {
     match private_ipv4() {
         Some(ip) => {
@@ -0,0 +1,68 @@
+#[cfg(not(feature = "std"))]
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicI64, Ordering};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+/// Supplies the current time to a [`Sonyflake`] generator.
+///
+/// The default clock used by [`Builder::finalize`] is [`SystemClock`]; inject
+/// a [`ManualClock`] (or your own implementation) via [`Builder::clock`] to
+/// drive generation deterministically, e.g. in tests.
+///
+/// [`Sonyflake`]: crate::Sonyflake
+/// [`Builder::finalize`]: crate::Builder::finalize
+/// [`Builder::clock`]: crate::Builder::clock
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> OffsetDateTime;
+}
+
+/// The default [`Clock`], backed by the system's real-time clock.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+/// A [`Clock`] that only advances when told to, for deterministic tests.
+///
+/// Cloning a `ManualClock` shares its underlying state, so a clone retained
+/// by the caller can advance the time seen by a [`Sonyflake`] built with the
+/// original.
+///
+/// [`Sonyflake`]: crate::Sonyflake
+#[derive(Clone)]
+pub struct ManualClock(Arc<AtomicI64>);
+
+impl ManualClock {
+    /// Create a manual clock starting at `now`.
+    pub fn new(now: OffsetDateTime) -> Self {
+        Self(Arc::new(AtomicI64::new(now.unix_timestamp_nanos() as i64)))
+    }
+
+    /// Set the clock to `now`.
+    pub fn set(&self, now: OffsetDateTime) {
+        self.0
+            .store(now.unix_timestamp_nanos() as i64, Ordering::SeqCst);
+    }
+
+    /// Advance the clock by `duration`.
+    pub fn advance(&self, duration: time::Duration) {
+        self.0
+            .fetch_add(duration.whole_nanoseconds() as i64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp_nanos(self.0.load(Ordering::SeqCst) as i128)
+            .expect("manual clock nanos should be a valid timestamp")
+    }
+}
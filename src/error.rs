@@ -1,4 +1,9 @@
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, string::String};
+#[cfg(feature = "std")]
 use std::error::Error as StdError;
+#[cfg(not(feature = "std"))]
+use core::error::Error as StdError;
 use thiserror::Error;
 use time::OffsetDateTime;
 
@@ -20,4 +25,10 @@ pub enum Error {
     OverSequenceLimit,
     #[error("could not find any private ipv4 address")]
     NoPrivateIPv4,
+    #[error("invalid bit length configuration: {0}")]
+    InvalidBitLength(String),
+    #[error("machine_id must be set explicitly when the `std-net` feature is disabled")]
+    MachineIdRequired,
+    #[error("clock must be set explicitly when the `std` feature is disabled")]
+    ClockRequired,
 }
@@ -0,0 +1,86 @@
+#[cfg(not(feature = "std"))]
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+use crate::{
+    error::Error,
+    sonyflake::{to_sonyflake_time, SharedSonyflake, Sonyflake},
+};
+
+/// A Sonyflake generator that never fails when the time component overflows.
+///
+/// [`Sonyflake::next_id`] returns [`Error::OverTimeLimit`] once the elapsed
+/// time since `start_time` no longer fits in the configured time bits, which
+/// permanently bricks a long-lived generator after roughly 174 years (or
+/// sooner, if `start_time` was misconfigured). `InfallibleSonyflake` instead
+/// resets `start_time` to the current time and zeroes the sequence state,
+/// then retries, so `next_id` only returns an error for
+/// [`Error::OverSequenceLimit`].
+///
+/// This comes at a cost: ids generated after a reset are **not guaranteed to
+/// be greater** than ids generated before it, so this type is only suitable
+/// for callers who don't depend on global monotonicity. Callers that need
+/// strict monotonicity should keep using [`Sonyflake`] directly.
+pub struct InfallibleSonyflake(pub(crate) Arc<SharedSonyflake>);
+
+impl InfallibleSonyflake {
+    pub(crate) fn new_inner(shared: Arc<SharedSonyflake>) -> Self {
+        Self(shared)
+    }
+
+    /// Generate the next unique id using the configured [`Clock`], resetting
+    /// the epoch instead of failing if the time component has overflowed.
+    ///
+    /// [`Clock`]: crate::Clock
+    pub fn next_id(&self) -> Result<u64, Error> {
+        self.next_id_at(self.0.clock.now())
+    }
+
+    /// Generate the next unique id as of `now`, resetting the epoch instead
+    /// of failing if the time component has overflowed.
+    ///
+    /// This can still return [`Error::OverSequenceLimit`] if more than
+    /// `1 << sequence` ids (for the configured sequence bit length) are
+    /// requested within the same 10ms tick;
+    /// callers should retry after a short sleep, as with
+    /// [`Sonyflake::next_id_at`].
+    pub fn next_id_at(&self, now: OffsetDateTime) -> Result<u64, Error> {
+        match Sonyflake::new_inner(self.0.clone()).next_id_at(now) {
+            Err(Error::OverTimeLimit) => {
+                self.reset(now);
+                Sonyflake::new_inner(self.0.clone()).next_id_at(now)
+            }
+            result => result,
+        }
+    }
+
+    /// Reset `start_time` to `now` and zero the sequence state so generation
+    /// can continue past the time limit.
+    ///
+    /// All three fields live behind the same `Internals` lock, so this reset
+    /// is a single atomic step: a concurrent [`Sonyflake::next_id_at`] call
+    /// on another thread either reads the pre-reset or post-reset state in
+    /// full, never a torn mix of a new `start_time` paired with the old
+    /// `elapsed_time`/`sequence`. If another call has already reset the
+    /// state since the time limit was hit, it is left as it is, so the
+    /// sequence is not rewound under ids already handed out.
+    fn reset(&self, now: OffsetDateTime) {
+        let time_limit = 1i64 << self.0.bit_len.time;
+        self.0.with_internals(|internals| {
+            if internals.elapsed_time >= time_limit {
+                internals.start_time = to_sonyflake_time(now);
+                internals.elapsed_time = 0;
+                internals.sequence = 0;
+            }
+        });
+    }
+}
+
+/// Returns a new `InfallibleSonyflake` referencing the same state as `self`.
+impl Clone for InfallibleSonyflake {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
@@ -1,9 +1,23 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! `no_std` callers must supply an explicit `machine_id` (the `std-net`
+//! feature's IP-based discovery is unavailable) and [`Clock`] (there's no
+//! real-time [`SystemClock`] without `std`), and pull in `alloc`, since
+//! [`Sonyflake`] is reference-counted via `Arc`.
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
 mod builder;
+mod clock;
 mod error;
+mod infallible;
 mod sonyflake;
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests;
 
 pub use crate::sonyflake::*;
 pub use builder::*;
+pub use clock::*;
 pub use error::*;
+pub use infallible::*;
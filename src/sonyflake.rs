@@ -1,7 +1,18 @@
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, sync::Arc};
+#[cfg(not(feature = "std"))]
+use core::cell::RefCell;
+#[cfg(not(feature = "std"))]
+use critical_section::Mutex;
+#[cfg(feature = "std")]
 use std::sync::{Arc, Mutex};
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::Duration;
 use time::OffsetDateTime;
 
-use crate::{builder::Builder, error::*};
+use crate::{builder::Builder, clock::Clock, error::*, infallible::InfallibleSonyflake};
 
 /// bit length of time
 pub(crate) const BIT_LEN_TIME: u64 = 39;
@@ -10,18 +21,92 @@ pub(crate) const BIT_LEN_SEQUENCE: u64 = 9;
 /// bit length of machine id
 pub(crate) const BIT_LEN_MACHINE_ID: u64 = 64 - BIT_LEN_TIME - BIT_LEN_SEQUENCE;
 
-const GENERATE_MASK_SEQUENCE: u16 = (1 << BIT_LEN_SEQUENCE) - 1;
+/// The bit-width layout used to lay a generated id out into time, sequence,
+/// and machine id components.
+///
+/// The three fields always sum to 64. Defaults to this crate's
+/// `time: 39, sequence: 9, machine_id: 16` split; see [`Builder::bit_lengths`]
+/// to customize it.
+///
+/// [`Builder::bit_lengths`]: crate::Builder::bit_lengths
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BitLen {
+    pub(crate) time: u8,
+    pub(crate) sequence: u8,
+    pub(crate) machine_id: u8,
+}
+
+impl Default for BitLen {
+    fn default() -> Self {
+        Self {
+            time: BIT_LEN_TIME as u8,
+            sequence: BIT_LEN_SEQUENCE as u8,
+            machine_id: BIT_LEN_MACHINE_ID as u8,
+        }
+    }
+}
+
+impl BitLen {
+    pub(crate) fn mask_sequence(&self) -> u16 {
+        ((1u32 << self.sequence) - 1) as u16
+    }
+
+    pub(crate) fn mask_machine_id(&self) -> u64 {
+        (1u64 << self.machine_id) - 1
+    }
+
+    pub(crate) fn shift_sequence(&self) -> u32 {
+        self.machine_id as u32
+    }
+
+    pub(crate) fn shift_time(&self) -> u32 {
+        self.sequence as u32 + self.machine_id as u32
+    }
+}
 
 #[derive(Debug)]
 pub(crate) struct Internals {
+    pub(crate) start_time: i64,
     pub(crate) elapsed_time: i64,
     pub(crate) sequence: u16,
 }
 
+/// Guards [`Internals`] behind a `std::sync::Mutex` when the `std` feature is
+/// enabled, or behind a `critical-section`-based lock under `no_std`.
+#[cfg(feature = "std")]
+pub(crate) type InternalsLock = Mutex<Internals>;
+#[cfg(not(feature = "std"))]
+pub(crate) type InternalsLock = Mutex<RefCell<Internals>>;
+
 pub(crate) struct SharedSonyflake {
-    pub(crate) start_time: i64,
     pub(crate) machine_id: u16,
-    pub(crate) internals: Mutex<Internals>,
+    pub(crate) bit_len: BitLen,
+    pub(crate) clock: Box<dyn Clock>,
+    pub(crate) internals: InternalsLock,
+}
+
+impl SharedSonyflake {
+    #[cfg(feature = "std")]
+    pub(crate) fn new_internals_lock(internals: Internals) -> InternalsLock {
+        Mutex::new(internals)
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn new_internals_lock(internals: Internals) -> InternalsLock {
+        Mutex::new(RefCell::new(internals))
+    }
+
+    /// Run `f` with exclusive access to the generator's [`Internals`].
+    #[cfg(feature = "std")]
+    pub(crate) fn with_internals<R>(&self, f: impl FnOnce(&mut Internals) -> R) -> R {
+        let mut internals = self.internals.lock().unwrap();
+        f(&mut internals)
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn with_internals<R>(&self, f: impl FnOnce(&mut Internals) -> R) -> R {
+        critical_section::with(|cs| f(&mut self.internals.borrow(cs).borrow_mut()))
+    }
 }
 
 /// Sonyflake is a distributed unique ID generator.
@@ -48,39 +133,127 @@ impl Sonyflake {
     }
 
     pub fn min_sonyflake_for_time(&self, time: OffsetDateTime) -> u64 {
-        ((to_sonyflake_time(time) - self.0.start_time) as u64)
-            << (BIT_LEN_SEQUENCE + BIT_LEN_MACHINE_ID)
+        let start_time = self.0.with_internals(|internals| internals.start_time);
+        ((to_sonyflake_time(time) - start_time) as u64) << self.0.bit_len.shift_time()
     }
 
-    /// Generate the next unique id.
+    /// Break a Sonyflake ID generated by this generator up into its parts,
+    /// using this generator's configured [`Builder::bit_lengths`].
+    ///
+    /// [`Builder::bit_lengths`]: crate::Builder::bit_lengths
+    pub fn decompose(&self, id: u64) -> DecomposedSonyflake {
+        let bit_len = &self.0.bit_len;
+        DecomposedSonyflake {
+            id,
+            time: id >> bit_len.shift_time(),
+            sequence: (id >> bit_len.shift_sequence()) & bit_len.mask_sequence() as u64,
+            machine_id: id & bit_len.mask_machine_id(),
+        }
+    }
+
+    /// Convert this generator into one that never fails when the time
+    /// component overflows.
+    ///
+    /// See [`InfallibleSonyflake`] for the monotonicity tradeoff this implies.
+    pub fn into_infallible(self) -> InfallibleSonyflake {
+        InfallibleSonyflake::new_inner(self.0)
+    }
+
+    /// Generate the next unique id using the configured [`Clock`] (the
+    /// system clock by default; see [`Builder::clock`] to inject another
+    /// one, e.g. a [`ManualClock`] for deterministic tests).
+    ///
     /// After the Sonyflake time overflows, next_id returns an error.
-    pub fn next_id(&self, now: OffsetDateTime) -> Result<u64, Error> {
-        let mut internals = self.0.internals.lock().unwrap();
-
-        let current = current_elapsed_time(now, self.0.start_time);
-        if internals.elapsed_time < current {
-            internals.elapsed_time = current;
-            internals.sequence = 0;
-        } else {
-            // self.elapsed_time >= current
-            let next_sequence = (internals.sequence + 1) & GENERATE_MASK_SEQUENCE;
-            if next_sequence == 0 {
-                // Overflowed. Caller will need to sleep or handle.
-                return Err(Error::OverSequenceLimit);
+    ///
+    /// [`Builder::clock`]: crate::Builder::clock
+    /// [`ManualClock`]: crate::ManualClock
+    pub fn next_id(&self) -> Result<u64, Error> {
+        self.next_id_at(self.0.clock.now())
+    }
+
+    /// Generate the next unique id as of `now`, ignoring the configured
+    /// [`Clock`]. After the Sonyflake time overflows, next_id_at returns an
+    /// error.
+    pub fn next_id_at(&self, now: OffsetDateTime) -> Result<u64, Error> {
+        let bit_len = self.0.bit_len;
+        let machine_id = self.0.machine_id;
+
+        self.0.with_internals(|internals| {
+            let current = current_elapsed_time(now, internals.start_time);
+
+            if internals.elapsed_time < current {
+                internals.elapsed_time = current;
+                internals.sequence = 0;
             } else {
-                internals.sequence = next_sequence;
+                // self.elapsed_time >= current
+                let next_sequence = internals.sequence.wrapping_add(1) & bit_len.mask_sequence();
+                if next_sequence == 0 {
+                    // Overflowed. Caller will need to sleep or handle.
+                    return Err(Error::OverSequenceLimit);
+                } else {
+                    internals.sequence = next_sequence;
+                }
+            }
+
+            if internals.elapsed_time >= 1 << bit_len.time {
+                return Err(Error::OverTimeLimit);
+            }
+
+            Ok(
+                (internals.elapsed_time as u64) << bit_len.shift_time()
+                    | (internals.sequence as u64) << bit_len.shift_sequence()
+                    | (machine_id as u64),
+            )
+        })
+    }
+
+    /// Generate the next unique id, blocking the current thread instead of
+    /// returning [`Error::OverSequenceLimit`] when the sequence for the
+    /// current tick is exhausted.
+    ///
+    /// This is the sleep-and-retry loop that most callers end up writing
+    /// themselves, built into the crate. Unlike a fixed 10ms sleep,
+    /// the wait is computed from the exact remaining time until the next
+    /// tick, so it wakes up as soon as a new sequence window opens.
+    #[cfg(feature = "std")]
+    pub fn next_id_blocking(&self) -> Result<u64, Error> {
+        loop {
+            match self.next_id() {
+                Err(Error::OverSequenceLimit) => {
+                    let now = self.0.clock.now();
+                    thread::sleep(self.duration_until_next_tick(now));
+                }
+                result => return result,
             }
         }
+    }
 
-        if internals.elapsed_time >= 1 << BIT_LEN_TIME {
-            return Err(Error::OverTimeLimit);
+    /// Async equivalent of [`Sonyflake::next_id_blocking`] that
+    /// `tokio::time::sleep`s instead of blocking the thread, so the
+    /// generator can be awaited from an async executor without stalling it.
+    #[cfg(feature = "tokio")]
+    pub async fn next_id_async(&self) -> Result<u64, Error> {
+        loop {
+            match self.next_id() {
+                Err(Error::OverSequenceLimit) => {
+                    let now = self.0.clock.now();
+                    tokio::time::sleep(self.duration_until_next_tick(now)).await
+                }
+                result => return result,
+            }
         }
+    }
 
-        Ok(
-            (internals.elapsed_time as u64) << (BIT_LEN_SEQUENCE + BIT_LEN_MACHINE_ID)
-                | (internals.sequence as u64) << BIT_LEN_MACHINE_ID
-                | (self.0.machine_id as u64),
-        )
+    /// Returns how long to wait until the tick after the one currently
+    /// recorded in `internals.elapsed_time`, relative to `now`.
+    #[cfg(any(feature = "std", feature = "tokio"))]
+    fn duration_until_next_tick(&self, now: OffsetDateTime) -> Duration {
+        let (start_time, next_tick) = self
+            .0
+            .with_internals(|internals| (internals.start_time, internals.elapsed_time + 1));
+        let target_nanos = (start_time + next_tick) * SONYFLAKE_TIME_UNIT;
+        let remaining_nanos = target_nanos - now.unix_timestamp_nanos() as i64;
+        Duration::from_nanos(remaining_nanos.max(0) as u64)
     }
 }
 
@@ -115,16 +288,17 @@ impl DecomposedSonyflake {
     }
 }
 
-const DECOMPOSE_MASK_SEQUENCE: u64 = ((1 << BIT_LEN_SEQUENCE) - 1) << BIT_LEN_MACHINE_ID;
-
-const MASK_MACHINE_ID: u64 = (1 << BIT_LEN_MACHINE_ID) - 1;
-
-/// Break a Sonyflake ID up into its parts.
+/// Break a Sonyflake ID up into its parts, assuming the default bit-length
+/// layout. For a generator built with [`Builder::bit_lengths`], use
+/// [`Sonyflake::decompose`] instead.
+///
+/// [`Builder::bit_lengths`]: crate::Builder::bit_lengths
 pub fn decompose(id: u64) -> DecomposedSonyflake {
+    let bit_len = BitLen::default();
     DecomposedSonyflake {
         id,
-        time: id >> (BIT_LEN_SEQUENCE + BIT_LEN_MACHINE_ID),
-        sequence: (id & DECOMPOSE_MASK_SEQUENCE) >> BIT_LEN_MACHINE_ID,
-        machine_id: id & MASK_MACHINE_ID,
+        time: id >> bit_len.shift_time(),
+        sequence: (id >> bit_len.shift_sequence()) & bit_len.mask_sequence() as u64,
+        machine_id: id & bit_len.mask_machine_id(),
     }
 }
@@ -12,38 +12,31 @@ use time::OffsetDateTime;
 
 use crate::{
     builder::lower_16_bit_private_ip,
+    clock::{Clock, ManualClock},
     error::*,
     sonyflake::{decompose, to_sonyflake_time, Sonyflake, BIT_LEN_SEQUENCE, BIT_LEN_TIME},
 };
 
-fn next_id_with_sleep(sf: &Sonyflake) -> Result<u64, Error> {
-    loop {
-        match sf.next_id(OffsetDateTime::now_utc()) {
-            Ok(id) => return Ok(id),
-            Err(Error::OverSequenceLimit) => {
-                thread::sleep(Duration::from_millis(10));
-            }
-            Err(e) => return Err(e),
-        }
-    }
-}
-
 #[test]
 fn test_next_id() -> Result<(), BoxDynError> {
     let sf = Sonyflake::new()?;
-    assert!(sf.next_id(OffsetDateTime::now_utc()).is_ok());
+    assert!(sf.next_id().is_ok());
     Ok(())
 }
 
 #[test]
 fn test_once() -> Result<(), BoxDynError> {
     let now = OffsetDateTime::now_utc();
-    let sf = Sonyflake::builder().start_time(now).finalize()?;
+    let clock = ManualClock::new(now);
+    let sf = Sonyflake::builder()
+        .start_time(now)
+        .clock(clock.clone())
+        .finalize()?;
 
     let sleep_time = 50;
-    thread::sleep(Duration::from_millis(10 * sleep_time));
+    clock.advance(time::Duration::milliseconds(10 * sleep_time as i64));
 
-    let id = sf.next_id(OffsetDateTime::now_utc())?;
+    let id = sf.next_id()?;
     let parts = decompose(id);
 
     let actual_time = parts.time;
@@ -62,17 +55,27 @@ fn test_once() -> Result<(), BoxDynError> {
 fn test_run_for_10s() -> Result<(), BoxDynError> {
     let now = OffsetDateTime::now_utc();
     let start_time = to_sonyflake_time(now);
-    let sf = Sonyflake::builder().start_time(now).finalize()?;
+    let clock = ManualClock::new(now);
+    let sf = Sonyflake::builder()
+        .start_time(now)
+        .clock(clock.clone())
+        .finalize()?;
 
     let mut last_id: u64 = 0;
     let mut max_sequence: u64 = 0;
 
     let machine_id = lower_16_bit_private_ip()? as u64;
 
-    let initial = to_sonyflake_time(OffsetDateTime::now_utc());
+    let initial = to_sonyflake_time(now);
     let mut current = initial;
     while current - initial < 1000 {
-        let id = next_id_with_sleep(&sf)?;
+        let id = match sf.next_id() {
+            Err(Error::OverSequenceLimit) => {
+                clock.advance(time::Duration::milliseconds(10));
+                continue;
+            }
+            result => result?,
+        };
         let parts = decompose(id);
 
         if id <= last_id {
@@ -80,7 +83,7 @@ fn test_run_for_10s() -> Result<(), BoxDynError> {
         }
         last_id = id;
 
-        current = to_sonyflake_time(OffsetDateTime::now_utc());
+        current = to_sonyflake_time(clock.now());
 
         let actual_time = parts.time as i64;
         let overtime = start_time + actual_time - current;
@@ -121,7 +124,7 @@ fn test_threads() -> Result<(), BoxDynError> {
         children.push(thread::spawn(move || {
             for _ in 0..1000 {
                 thread_tx
-                    .send(next_id_with_sleep(&thread_sf).unwrap())
+                    .send(thread_sf.next_id_blocking().unwrap())
                     .unwrap();
             }
         }));
@@ -141,13 +144,41 @@ fn test_threads() -> Result<(), BoxDynError> {
     Ok(())
 }
 
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_next_id_async() -> Result<(), BoxDynError> {
+    let sf = Sonyflake::new()?;
+
+    let mut tasks = Vec::new();
+    for _ in 0..10 {
+        let task_sf = sf.clone();
+        tasks.push(tokio::spawn(async move {
+            let mut ids = Vec::with_capacity(1000);
+            for _ in 0..1000 {
+                ids.push(task_sf.next_id_async().await.unwrap());
+            }
+            ids
+        }));
+    }
+
+    let mut ids = HashSet::new();
+    for task in tasks {
+        for id in task.await.expect("task panicked") {
+            assert!(!ids.contains(&id), "duplicate id: {}", id);
+            ids.insert(id);
+        }
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_generate_10_ids() -> Result<(), BoxDynError> {
     let sf = Sonyflake::builder().machine_id(&|| Ok(42)).finalize()?;
     let mut ids = vec![];
     for _ in 0..10 {
-        let id = sf.next_id(OffsetDateTime::now_utc())?;
-        if ids.iter().any(|vec_id| *vec_id == id) {
+        let id = sf.next_id()?;
+        if ids.contains(&id) {
             panic!("duplicated id: {}", id)
         }
         ids.push(id);
@@ -181,6 +212,44 @@ fn test_builder_errors() {
         Err(Error::CheckMachineIdFailed) => {}
         _ => panic!("Expected error on check_machine_id closure returning false"),
     }
+
+    match Sonyflake::builder().bit_lengths(40, 30).finalize() {
+        Err(Error::InvalidBitLength(_)) => {}
+        _ => panic!("Expected error on bit lengths summing to more than 64"),
+    }
+
+    match Sonyflake::builder()
+        .bit_lengths(43, 16)
+        .machine_id(&|| Ok(42))
+        .finalize()
+    {
+        Err(Error::InvalidBitLength(_)) => {}
+        _ => panic!("Expected error on machine_id not fitting in its configured width"),
+    }
+
+    match Sonyflake::builder()
+        .bit_lengths(10, 54)
+        .machine_id(&|| Ok(0))
+        .finalize()
+    {
+        Err(Error::InvalidBitLength(_)) => {}
+        _ => panic!("Expected error on sequence bits not fitting in the u16 sequence counter"),
+    }
+}
+
+#[test]
+fn test_custom_bit_lengths() -> Result<(), BoxDynError> {
+    // Classic Sony layout: 8 sequence bits instead of this crate's default 9.
+    let sf = Sonyflake::builder()
+        .bit_lengths(39, 8)
+        .machine_id(&|| Ok(42))
+        .finalize()?;
+
+    let id = sf.next_id()?;
+    let parts = sf.decompose(id);
+    assert_eq!(parts.machine_id, 42);
+
+    Ok(())
 }
 
 #[test]
@@ -196,9 +265,26 @@ fn test_error_send_sync() {
 #[test]
 fn test_over_time_limit() -> Result<(), BoxDynError> {
     let sf = Sonyflake::new()?;
-    let mut internals = sf.0.internals.lock().unwrap();
-    internals.elapsed_time = 1 << BIT_LEN_TIME;
-    drop(internals);
-    assert!(sf.next_id(OffsetDateTime::now_utc()).is_err());
+    sf.0.with_internals(|internals| internals.elapsed_time = 1 << BIT_LEN_TIME);
+    assert!(sf.next_id().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_infallible_resets_on_time_overflow() -> Result<(), BoxDynError> {
+    let now = OffsetDateTime::now_utc();
+    let sf = Sonyflake::builder()
+        .clock(ManualClock::new(now))
+        .finalize()?
+        .into_infallible();
+    sf.0.with_internals(|internals| internals.elapsed_time = 1 << BIT_LEN_TIME);
+
+    let id = sf.next_id()?;
+    let parts = decompose(id);
+    assert!(
+        (parts.time as i64) < 1 << BIT_LEN_TIME,
+        "expected elapsed_time to have been reset"
+    );
+
     Ok(())
 }